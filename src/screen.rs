@@ -1,4 +1,13 @@
+mod cell;
+mod search;
+mod selection;
+mod width;
+
 use crate::ansi::*;
+use cell::{Cell, LogicalLine};
+use copypasta::{ClipboardContext, ClipboardProvider};
+use search::{Direction as SearchDirection, Search};
+use selection::{Mode as SelectionMode, Point as SelectionPoint, Selection};
 use std::collections::VecDeque;
 use std::io::{stdout, Stdout, Write};
 use termion::{
@@ -6,23 +15,47 @@ use termion::{
     raw::{IntoRawMode, RawTerminal},
     screen::AlternateScreen,
 };
-use textwrap;
 
-struct ScrollData(bool, usize);
 const OUTPUT_START_LINE: u16 = 2;
 
+/// Default depth of the scrollback buffer, in logical (unwrapped) lines.
+pub const DEFAULT_SCROLLBACK_SIZE: usize = 1024;
+
+/// How far the visible window is scrolled up from the live bottom, in
+/// wrapped display rows. `0` means the window tracks the live tail.
+#[derive(Default)]
+struct Scrollback {
+    offset: usize,
+}
+
+impl Scrollback {
+    fn is_scrolled(&self) -> bool {
+        self.offset > 0
+    }
+}
+
 pub struct Screen {
     screen: AlternateScreen<RawTerminal<Stdout>>,
     width: u16,
     _height: u16,
     output_line: u16,
     prompt_line: u16,
-    history: VecDeque<String>,
-    scroll_data: ScrollData,
+    history: VecDeque<LogicalLine>,
+    scrollback_size: usize,
+    scrollback: Scrollback,
+    selection: Option<Selection>,
+    search: Option<Search>,
 }
 
 impl Screen {
     pub fn new() -> Self {
+        Self::with_scrollback_size(DEFAULT_SCROLLBACK_SIZE)
+    }
+
+    pub fn with_scrollback_size(scrollback_size: usize) -> Self {
+        // A size of 0 would make `append_to_history`'s trim loop drop every
+        // line as soon as it is pushed, leaving nothing to draw, so floor it.
+        let scrollback_size = scrollback_size.max(1);
         let screen = AlternateScreen::from(stdout().into_raw_mode().unwrap());
         let (width, height) = termion::terminal_size().unwrap();
 
@@ -35,8 +68,11 @@ impl Screen {
             _height: height,
             output_line,
             prompt_line,
-            history: VecDeque::with_capacity(1024),
-            scroll_data: ScrollData(false, 0),
+            history: VecDeque::with_capacity(scrollback_size),
+            scrollback_size,
+            scrollback: Scrollback::default(),
+            selection: None,
+            search: None,
         }
     }
 
@@ -57,16 +93,7 @@ impl Screen {
             DisableOriginMode
         )
         .unwrap(); // Set scroll region, non origin mode
-        write!(
-            self.screen,
-            "{}{}{}",
-            termion::cursor::Goto(1, 1),
-            termion::clear::CurrentLine,
-            color::Fg(color::Green),
-        )
-        .unwrap();
-        write!(self.screen, "{:=<1$}", "", self.width as usize).unwrap(); // Print separator
-        write!(self.screen, "{}", color::Fg(color::Reset)).unwrap();
+        self.draw_top_separator();
         write!(
             self.screen,
             "{}{}{}",
@@ -77,6 +104,11 @@ impl Screen {
         .unwrap();
         write!(self.screen, "{:_<1$}", "", self.width as usize).unwrap(); // Print separator
         write!(self.screen, "{}", color::Fg(color::Reset)).unwrap();
+
+        // Re-wrap and repaint the output pane against the new width/height so
+        // a resize never leaves stale, incorrectly-broken lines on screen.
+        self.draw_scroll();
+
         self.screen.flush().unwrap();
     }
 
@@ -86,7 +118,7 @@ impl Screen {
 
     pub fn print_prompt(&mut self, prompt: &str) {
         self.append_to_history(prompt);
-        if !self.scroll_data.0 {
+        if !self.scrollback.is_scrolled() {
             write!(
                 self.screen,
                 "{}{}{}{}",
@@ -96,15 +128,18 @@ impl Screen {
                 termion::cursor::Goto(1, self.prompt_line),
             )
             .unwrap();
+        } else {
+            self.draw_scroll();
         }
     }
 
     pub fn print_prompt_input(&mut self, input: &str) {
-        let mut input = input;
-        while input.len() >= self.width as usize {
-            let (_, last) = input.split_at(self.width as usize);
-            input = last;
-        }
+        let width = self.width as usize;
+        let input = if width::columns(input) > width {
+            width::tail_columns(input, width)
+        } else {
+            input
+        };
         write!(
             self.screen,
             "{}{}{}",
@@ -119,27 +154,44 @@ impl Screen {
         if line.trim().is_empty() {
             self.print_line(&line);
         } else {
-            for line in textwrap::wrap_iter(line, self.width as usize) {
-                self.print_line(&line);
+            let logicals = self.append_to_history(line);
+            if self.scrollback.is_scrolled() {
+                // The window trails the live tail by a constant offset, so
+                // new rows shift it forward and it must be fully repainted.
+                self.draw_scroll();
+            } else {
+                for logical in &logicals {
+                    for row in logical.wrap(self.width as usize) {
+                        self.draw_row(&row);
+                    }
+                }
             }
         }
     }
 
     fn print_line(&mut self, line: &str) {
-        self.append_to_history(&line);
-        if !self.scroll_data.0 {
-            write!(
-                self.screen,
-                "{}{}{}{}",
-                termion::cursor::Goto(1, self.output_line),
-                termion::scroll::Up(1),
-                &line,
-                termion::cursor::Goto(1, self.prompt_line)
-            )
-            .unwrap();
+        let logicals = self.append_to_history(line);
+        if self.scrollback.is_scrolled() {
+            self.draw_scroll();
+        } else {
+            for logical in &logicals {
+                self.draw_row(&logical.cells);
+            }
         }
     }
 
+    fn draw_row(&mut self, row: &[Cell]) {
+        write!(
+            self.screen,
+            "{}{}{}{}",
+            termion::cursor::Goto(1, self.output_line),
+            termion::scroll::Up(1),
+            LogicalLine::render_row(row),
+            termion::cursor::Goto(1, self.prompt_line)
+        )
+        .unwrap();
+    }
+
     pub fn print_send(&mut self, send: &str) {
         self.print_output(&format!(
             "{}> {}{}",
@@ -162,92 +214,298 @@ impl Screen {
         ));
     }
 
-    pub fn scroll_up(&mut self) {
-        let output_range: usize = self.output_line as usize - OUTPUT_START_LINE as usize;
-        if self.history.len() > output_range as usize {
-            if !self.scroll_data.0 {
-                self.scroll_data.0 = true;
-                self.scroll_data.1 = self.history.len() - output_range;
-            }
-            self.scroll_data.0 = true;
-            self.scroll_data.1 -= self.scroll_data.1.min(5);
-            self.draw_scroll();
-        }
+    /// Scroll the view by `delta` display rows; positive moves back in time
+    /// (up), negative moves forward (down). Clamped to `[0, history top]`.
+    pub fn scroll_lines(&mut self, delta: isize) {
+        let output_range = self.output_range() as isize;
+        let max_offset = self.wrapped_rows().len() as isize - output_range;
+        let max_offset = max_offset.max(0);
+        let offset = self.scrollback.offset as isize + delta;
+        self.scrollback.offset = offset.max(0).min(max_offset) as usize;
+        self.draw_scroll();
     }
 
-    pub fn scroll_down(&mut self) {
-        if self.scroll_data.0 {
-            let output_range: i32 = self.output_line as i32 - OUTPUT_START_LINE as i32;
-            let max_start_index: i32 = self.history.len() as i32 - output_range;
-            let new_start_index = self.scroll_data.1 + 5;
-            if new_start_index >= max_start_index as usize {
-                self.reset_scroll();
-            } else {
-                self.scroll_data.1 = new_start_index;
-                self.draw_scroll();
-            }
-        }
+    pub fn scroll_to_top(&mut self) {
+        let output_range = self.output_range() as isize;
+        let max_offset = self.wrapped_rows().len() as isize - output_range;
+        self.scrollback.offset = max_offset.max(0) as usize;
+        self.draw_scroll();
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.scrollback.offset = 0;
+        self.draw_scroll();
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_lines(self.output_range() as isize);
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_lines(-(self.output_range() as isize));
+    }
+
+    /// Number of display rows the output pane can show at once.
+    fn output_range(&self) -> u16 {
+        self.output_line - OUTPUT_START_LINE + 1
     }
 
     fn draw_scroll(&mut self) {
-        let output_range = self.output_line - OUTPUT_START_LINE + 1;
+        let rows = self.wrapped_rows_with_origin();
+        let output_range = self.output_range() as usize;
+        let start_index = rows.len().saturating_sub(output_range + self.scrollback.offset);
         for i in 0..output_range {
-            let index = self.scroll_data.1 + i as usize;
-            let line_no = OUTPUT_START_LINE + i;
+            let index = start_index + i;
+            let line_no = OUTPUT_START_LINE + i as u16;
+            let rendered = rows
+                .get(index)
+                .map(|(line, col_start, row)| self.render_row_decorated(*line, *col_start, row))
+                .unwrap_or_default();
             write!(
                 self.screen,
                 "{}{}{}",
                 termion::cursor::Goto(1, line_no),
                 termion::clear::CurrentLine,
-                self.history[index],
+                rendered,
             )
             .unwrap();
         }
+        self.draw_top_separator();
     }
 
-    pub fn reset_scroll(&mut self) {
-        self.scroll_data.0 = false;
-        let output_range = self.output_line - OUTPUT_START_LINE + 1;
-        let output_start_index = self.history.len() as i32 - output_range as i32;
-        if output_start_index >= 0 {
-            let output_start_index = output_start_index as usize;
-            for i in 0..output_range {
-                let index = output_start_index + i as usize;
-                let line_no = OUTPUT_START_LINE + i;
-                write!(
-                    self.screen,
-                    "{}{}{}",
-                    termion::cursor::Goto(1, line_no),
-                    termion::clear::CurrentLine,
-                    self.history[index],
-                )
-                .unwrap();
+    /// Render a display row, applying the active selection's and/or active
+    /// search's highlighting to the cells that fall within them.
+    fn render_row_decorated(&self, line: usize, col_start: usize, row: &[Cell]) -> String {
+        if self.selection.is_none() && self.search.is_none() {
+            return LogicalLine::render_row(row);
+        }
+        let cells: Vec<Cell> = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| self.decorate_cell(line, col_start + i, *cell))
+            .collect();
+        LogicalLine::render_row(&cells)
+    }
+
+    fn decorate_cell(&self, line: usize, column: usize, cell: Cell) -> Cell {
+        if let Some(selection) = &self.selection {
+            if selection.contains(&self.history, line, column) {
+                return cell.inverted();
             }
-        } else {
-            for line in &self.history {
-                write!(
-                    self.screen,
-                    "{}{}{}",
-                    termion::cursor::Goto(1, self.output_line),
-                    termion::scroll::Up(1),
-                    line,
-                )
-                .unwrap();
+        }
+        if let Some(search) = &self.search {
+            if let Some(current) = search.contains(line, column) {
+                return cell.highlighted(current);
+            }
+        }
+        cell
+    }
+
+    /// Map a clicked/dragged screen position back to a (logical line,
+    /// column) point. `screen_row` is a terminal line as passed to
+    /// `cursor::Goto` (within `OUTPUT_START_LINE..=output_line`);
+    /// `screen_column` is a 0-indexed display column.
+    fn screen_to_point(&self, screen_row: u16, screen_column: u16) -> Option<SelectionPoint> {
+        if screen_row < OUTPUT_START_LINE || screen_row > self.output_line {
+            return None;
+        }
+        let rows = self.wrapped_rows_with_origin();
+        let output_range = self.output_range() as usize;
+        let start_index = rows.len().saturating_sub(output_range + self.scrollback.offset);
+        let index = start_index + (screen_row - OUTPUT_START_LINE) as usize;
+        let (line, col_start, row) = rows.get(index)?;
+        let cell_offset = LogicalLine::cell_index_for_column(row, screen_column as usize);
+        Some(SelectionPoint {
+            line: *line,
+            column: col_start + cell_offset,
+        })
+    }
+
+    /// Begin a new selection anchored at a clicked screen position.
+    pub fn start_selection(&mut self, screen_row: u16, screen_column: u16, mode: SelectionMode) {
+        if let Some(point) = self.screen_to_point(screen_row, screen_column) {
+            self.selection = Some(Selection::new(point, mode));
+            self.draw_scroll();
+        }
+    }
+
+    /// Extend the active selection to a dragged-to screen position.
+    pub fn update_selection(&mut self, screen_row: u16, screen_column: u16) {
+        if let (Some(selection), Some(point)) = (
+            self.selection.as_mut(),
+            self.screen_to_point(screen_row, screen_column),
+        ) {
+            selection.update_cursor(point);
+            self.draw_scroll();
+        }
+    }
+
+    /// Drop the active selection, if any.
+    pub fn clear_selection(&mut self) {
+        if self.selection.take().is_some() {
+            self.draw_scroll();
+        }
+    }
+
+    /// Flatten the active selection's cells back to plain text (no ANSI)
+    /// and push it to the system clipboard.
+    pub fn copy_selection(&mut self) {
+        let selection = match &self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let (start, end) = selection.normalized(&self.history);
+        let mut text = String::new();
+        for line in start.line..=end.line {
+            if let Some(logical) = self.history.get(line) {
+                let from = if line == start.line { start.column } else { 0 };
+                let to = if line == end.line {
+                    end.column
+                } else {
+                    logical.cells.len()
+                };
+                for cell in logical.cells.iter().take(to).skip(from) {
+                    text.push(cell.ch);
+                }
+            }
+            if line != end.line {
+                text.push('\n');
             }
         }
+        if let Ok(mut clipboard) = ClipboardContext::new() {
+            let _ = clipboard.set_contents(text);
+        }
+    }
+
+    /// Old name for `scroll_to_bottom`, kept as the entry point that resets
+    /// the view after e.g. the user submits a command.
+    pub fn reset_scroll(&mut self) {
+        self.scroll_to_bottom();
+    }
+
+    /// Search the scrollback for `pattern`, scrolling to and highlighting
+    /// the nearest match in `direction` from the currently visible view.
+    pub fn search(&mut self, pattern: &str, direction: SearchDirection, case_insensitive: bool) {
+        let from_line = self.top_visible_line();
+        self.search = Search::new(&self.history, pattern, case_insensitive);
+        if let Some(line) = self
+            .search
+            .as_mut()
+            .and_then(|search| search.seek_from(from_line, direction))
+            .map(|m| m.line)
+        {
+            self.jump_to_line(line);
+        }
+        self.draw_scroll();
+    }
+
+    /// Advance to the next/previous match of the active search, wrapping
+    /// around at the ends.
+    pub fn search_next(&mut self, direction: SearchDirection) {
+        if let Some(line) = self
+            .search
+            .as_mut()
+            .and_then(|search| search.advance(direction))
+            .map(|m| m.line)
+        {
+            self.jump_to_line(line);
+        }
+        self.draw_scroll();
+    }
+
+    /// Drop the active search and its highlighting.
+    pub fn clear_search(&mut self) {
+        self.search = None;
+        self.draw_scroll();
+    }
+
+    /// The logical line backing the topmost row currently in view.
+    fn top_visible_line(&self) -> usize {
+        let rows = self.wrapped_rows_with_origin();
+        let output_range = self.output_range() as usize;
+        let start_index = rows.len().saturating_sub(output_range + self.scrollback.offset);
+        rows.get(start_index).map_or(0, |(line, _, _)| *line)
+    }
+
+    /// Scroll so that logical line `line`'s first row is the top of the
+    /// visible window, or as near the top as the end of history allows.
+    fn jump_to_line(&mut self, line: usize) {
+        let rows = self.wrapped_rows_with_origin();
+        let output_range = self.output_range() as usize;
+        if rows.len() <= output_range {
+            self.scrollback.offset = 0;
+            return;
+        }
+        let row_index = rows.iter().position(|(l, _, _)| *l == line).unwrap_or(0);
+        let max_start = rows.len() - output_range;
+        let start_index = row_index.min(max_start);
+        self.scrollback.offset = max_start - start_index;
+    }
+
+    /// Draw the green separator above the output pane, with a centered
+    /// " MORE (N lines below) " marker while the view is scrolled back.
+    fn draw_top_separator(&mut self) {
+        write!(
+            self.screen,
+            "{}{}{}",
+            termion::cursor::Goto(1, 1),
+            termion::clear::CurrentLine,
+            color::Fg(color::Green),
+        )
+        .unwrap();
+        if self.scrollback.is_scrolled() {
+            let marker = format!(" MORE ({} lines below) ", self.scrollback.offset);
+            write!(self.screen, "{:=^1$}", marker, self.width as usize).unwrap();
+        } else {
+            write!(self.screen, "{:=<1$}", "", self.width as usize).unwrap();
+        }
+        write!(self.screen, "{}", color::Fg(color::Reset)).unwrap();
     }
 
     pub fn flush(&mut self) {
         self.screen.flush().unwrap();
     }
 
-    fn append_to_history(&mut self, line: &str) {
-        let lines = line.split("\r\n");
-        for line in lines {
-            self.history.push_back(String::from(line));
+    fn append_to_history(&mut self, line: &str) -> Vec<LogicalLine> {
+        let mut logicals = Vec::new();
+        for segment in line.split("\r\n") {
+            let logical = LogicalLine::from_ansi(segment);
+            self.history.push_back(logical.clone());
+            logicals.push(logical);
         }
-        while self.history.len() >= self.history.capacity() {
-            self.history.pop_back();
+        while self.history.len() > self.scrollback_size {
+            self.history.pop_front();
+        }
+        logicals
+    }
+
+    /// Re-wrap every logical line in history against the current width,
+    /// producing the flattened list of display rows the scroll/draw
+    /// machinery indexes into.
+    fn wrapped_rows(&self) -> Vec<Vec<Cell>> {
+        self.wrapped_rows_with_origin()
+            .into_iter()
+            .map(|(_, _, row)| row)
+            .collect()
+    }
+
+    /// Like `wrapped_rows`, but each row is tagged with the logical line it
+    /// came from and the column it starts at, so a display position can be
+    /// mapped back to a stable (line, column) point (e.g. for selection).
+    fn wrapped_rows_with_origin(&self) -> Vec<(usize, usize, Vec<Cell>)> {
+        let mut rows = Vec::new();
+        for (line, logical) in self.history.iter().enumerate() {
+            if logical.is_empty() {
+                rows.push((line, 0, Vec::new()));
+            } else {
+                let mut column = 0;
+                for row in logical.wrap(self.width as usize) {
+                    let len = row.len();
+                    rows.push((line, column, row));
+                    column += len;
+                }
+            }
         }
+        rows
     }
 }
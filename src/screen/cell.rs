@@ -0,0 +1,504 @@
+//! Styled cell model for `Screen`'s history.
+//!
+//! Output is stored as logical (unwrapped) lines of [`Cell`]s rather than
+//! finished strings. Each cell carries the SGR attributes that were active
+//! when it was printed, so a line can be re-wrapped against any terminal
+//! width without losing or bleeding color across the wrap boundary.
+
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flags(u8);
+
+impl Flags {
+    pub const NONE: Flags = Flags(0);
+    pub const BOLD: Flags = Flags(1 << 0);
+    pub const ITALIC: Flags = Flags(1 << 1);
+    pub const UNDERLINE: Flags = Flags(1 << 2);
+    pub const INVERSE: Flags = Flags(1 << 3);
+
+    pub fn contains(self, flag: Flags) -> bool {
+        self.0 & flag.0 == flag.0
+    }
+
+    fn insert(&mut self, flag: Flags) {
+        self.0 |= flag.0;
+    }
+
+    fn remove(&mut self, flag: Flags) {
+        self.0 &= !flag.0;
+    }
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags::NONE
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    LightBlack,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+    LightWhite,
+}
+
+impl AnsiColor {
+    fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            0 => AnsiColor::Black,
+            1 => AnsiColor::Red,
+            2 => AnsiColor::Green,
+            3 => AnsiColor::Yellow,
+            4 => AnsiColor::Blue,
+            5 => AnsiColor::Magenta,
+            6 => AnsiColor::Cyan,
+            7 => AnsiColor::White,
+            _ => return None,
+        })
+    }
+
+    fn to_light(self) -> Self {
+        match self {
+            AnsiColor::Black => AnsiColor::LightBlack,
+            AnsiColor::Red => AnsiColor::LightRed,
+            AnsiColor::Green => AnsiColor::LightGreen,
+            AnsiColor::Yellow => AnsiColor::LightYellow,
+            AnsiColor::Blue => AnsiColor::LightBlue,
+            AnsiColor::Magenta => AnsiColor::LightMagenta,
+            AnsiColor::Cyan => AnsiColor::LightCyan,
+            AnsiColor::White => AnsiColor::LightWhite,
+            light => light,
+        }
+    }
+
+    fn code(self) -> u16 {
+        match self {
+            AnsiColor::Black => 0,
+            AnsiColor::Red => 1,
+            AnsiColor::Green => 2,
+            AnsiColor::Yellow => 3,
+            AnsiColor::Blue => 4,
+            AnsiColor::Magenta => 5,
+            AnsiColor::Cyan => 6,
+            AnsiColor::White => 7,
+            AnsiColor::LightBlack => 60,
+            AnsiColor::LightRed => 61,
+            AnsiColor::LightGreen => 62,
+            AnsiColor::LightYellow => 63,
+            AnsiColor::LightBlue => 64,
+            AnsiColor::LightMagenta => 65,
+            AnsiColor::LightCyan => 66,
+            AnsiColor::LightWhite => 67,
+        }
+    }
+
+    fn fg_code(self) -> u16 {
+        30 + self.code()
+    }
+
+    fn bg_code(self) -> u16 {
+        40 + self.code()
+    }
+}
+
+/// A resolved SGR color: one of the 16 named colors, an xterm-256 palette
+/// index (`38;5;n` / `48;5;n`), or a truecolor RGB triple (`38;2;r;g;b` /
+/// `48;2;r;g;b`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Named(AnsiColor),
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub flags: Flags,
+}
+
+impl Style {
+    /// The same style, forced into reverse video. Used to highlight a text
+    /// selection without disturbing the cell's original color/attributes.
+    pub fn inverted(mut self) -> Self {
+        self.flags.insert(Flags::INVERSE);
+        self
+    }
+
+    /// Apply a parsed `ESC [ params m` sequence to this style.
+    fn apply_sgr(&mut self, params: &[u16]) {
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => *self = Style::default(),
+                1 => self.flags.insert(Flags::BOLD),
+                3 => self.flags.insert(Flags::ITALIC),
+                4 => self.flags.insert(Flags::UNDERLINE),
+                7 => self.flags.insert(Flags::INVERSE),
+                22 => self.flags.remove(Flags::BOLD),
+                23 => self.flags.remove(Flags::ITALIC),
+                24 => self.flags.remove(Flags::UNDERLINE),
+                27 => self.flags.remove(Flags::INVERSE),
+                30..=37 => self.fg = AnsiColor::from_code(params[i] - 30).map(Color::Named),
+                38 => {
+                    if let Some((color, consumed)) = parse_extended_color(&params[i + 1..]) {
+                        self.fg = Some(color);
+                        i += consumed;
+                    }
+                }
+                39 => self.fg = None,
+                40..=47 => self.bg = AnsiColor::from_code(params[i] - 40).map(Color::Named),
+                48 => {
+                    if let Some((color, consumed)) = parse_extended_color(&params[i + 1..]) {
+                        self.bg = Some(color);
+                        i += consumed;
+                    }
+                }
+                49 => self.bg = None,
+                90..=97 => {
+                    self.fg = AnsiColor::from_code(params[i] - 90)
+                        .map(|color| Color::Named(color.to_light()))
+                }
+                100..=107 => {
+                    self.bg = AnsiColor::from_code(params[i] - 100)
+                        .map(|color| Color::Named(color.to_light()))
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// The full SGR sequence that puts a fresh terminal into this style.
+    fn to_sgr(self) -> String {
+        let mut codes = vec!["0".to_string()];
+        if self.flags.contains(Flags::BOLD) {
+            codes.push("1".to_string());
+        }
+        if self.flags.contains(Flags::ITALIC) {
+            codes.push("3".to_string());
+        }
+        if self.flags.contains(Flags::UNDERLINE) {
+            codes.push("4".to_string());
+        }
+        if self.flags.contains(Flags::INVERSE) {
+            codes.push("7".to_string());
+        }
+        if let Some(fg) = self.fg {
+            push_color_codes(&mut codes, fg, true);
+        }
+        if let Some(bg) = self.bg {
+            push_color_codes(&mut codes, bg, false);
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+/// Parse the sub-params following a `38`/`48` introducer: `5;<n>` (256-color
+/// palette index) or `2;<r>;<g>;<b>` (truecolor). Returns the color and how
+/// many of `rest` were consumed.
+fn parse_extended_color(rest: &[u16]) -> Option<(Color, usize)> {
+    match *rest.first()? {
+        5 => Some((Color::Indexed(*rest.get(1)? as u8), 2)),
+        2 => Some((
+            Color::Rgb(*rest.get(1)? as u8, *rest.get(2)? as u8, *rest.get(3)? as u8),
+            4,
+        )),
+        _ => None,
+    }
+}
+
+fn push_color_codes(codes: &mut Vec<String>, color: Color, is_fg: bool) {
+    match color {
+        Color::Named(named) => {
+            codes.push((if is_fg { named.fg_code() } else { named.bg_code() }).to_string())
+        }
+        Color::Indexed(n) => {
+            codes.push(if is_fg { "38" } else { "48" }.to_string());
+            codes.push("5".to_string());
+            codes.push(n.to_string());
+        }
+        Color::Rgb(r, g, b) => {
+            codes.push(if is_fg { "38" } else { "48" }.to_string());
+            codes.push("2".to_string());
+            codes.push(r.to_string());
+            codes.push(g.to_string());
+            codes.push(b.to_string());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cell {
+    pub ch: char,
+    pub style: Style,
+}
+
+impl Cell {
+    /// The same cell, styled to stand out as part of a selection.
+    pub fn inverted(self) -> Self {
+        Self {
+            style: self.style.inverted(),
+            ..self
+        }
+    }
+
+    /// The same cell, styled to stand out as a search match. The current
+    /// match is additionally bolded to set it apart from the others.
+    pub fn highlighted(self, current: bool) -> Self {
+        let mut style = self.style.inverted();
+        if current {
+            style.flags.insert(Flags::BOLD);
+        }
+        Self { style, ..self }
+    }
+}
+
+/// An unwrapped line of output, as it was originally printed.
+#[derive(Debug, Clone, Default)]
+pub struct LogicalLine {
+    pub cells: Vec<Cell>,
+}
+
+impl LogicalLine {
+    /// Parse a raw line, consuming SGR escape sequences into per-cell style
+    /// rather than leaving them embedded in the text.
+    pub fn from_ansi(raw: &str) -> Self {
+        let mut style = Style::default();
+        let mut cells = Vec::with_capacity(raw.len());
+        let mut chars = raw.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' && chars.peek() == Some(&'[') {
+                chars.next();
+                let mut buf = String::new();
+                let mut final_byte = None;
+                while let Some(&d) = chars.peek() {
+                    chars.next();
+                    // A CSI sequence ends at its first final byte (0x40-0x7e);
+                    // only `m` (SGR) carries style, anything else (cursor
+                    // moves, clears, ...) is consumed and otherwise ignored.
+                    if ('\x40'..='\x7e').contains(&d) {
+                        final_byte = Some(d);
+                        break;
+                    }
+                    buf.push(d);
+                }
+                if final_byte == Some('m') {
+                    let params: Vec<u16> = if buf.is_empty() {
+                        vec![0]
+                    } else {
+                        buf.split(';').filter_map(|p| p.parse().ok()).collect()
+                    };
+                    style.apply_sgr(&params);
+                }
+                continue;
+            }
+            if c == '\x1b' {
+                // A non-CSI escape introducer (OSC `ESC ]`, DCS `ESC P`,
+                // charset selects, or a bare trailing `ESC`): we don't carry
+                // any style out of these, so just drop the introducer and
+                // its immediate byte rather than re-emitting a literal
+                // control character into the pane.
+                chars.next();
+                continue;
+            }
+            cells.push(Cell { ch: c, style });
+        }
+        Self { cells }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Re-wrap this line's cells into rows no wider than `width` columns,
+    /// breaking after the last space (' ') before the boundary when there is one.
+    ///
+    /// Wrapping counts display columns, not cells: a wide glyph (CJK, emoji)
+    /// counts as two columns and a zero-width combining mark as none, so a
+    /// row never ends up visually wider than `width`.
+    pub fn wrap(&self, width: usize) -> Vec<Vec<Cell>> {
+        if width == 0 || self.cells.is_empty() {
+            return vec![self.cells.clone()];
+        }
+
+        let mut rows = Vec::new();
+        let mut row: Vec<Cell> = Vec::new();
+        let mut row_width = 0;
+        let mut last_space = None;
+        for &cell in &self.cells {
+            let cell_width = cell.ch.width().unwrap_or(0);
+            // Check before appending: a 2-column glyph landing on an odd
+            // boundary must start the next row rather than spill past `width`.
+            if !row.is_empty() && row_width + cell_width > width {
+                match last_space.take() {
+                    Some((at, _)) if at < row.len() => {
+                        let rest = row.split_off(at);
+                        rows.push(row);
+                        row = rest;
+                        row_width = row.iter().filter_map(|c| c.ch.width()).sum();
+                    }
+                    _ => {
+                        rows.push(row);
+                        row = Vec::new();
+                        row_width = 0;
+                    }
+                }
+            }
+            row.push(cell);
+            row_width += cell_width;
+            if cell.ch == ' ' {
+                last_space = Some((row.len(), row_width));
+            }
+        }
+        if !row.is_empty() || rows.is_empty() {
+            rows.push(row);
+        }
+        rows
+    }
+
+    /// The index of the cell displayed at `column` (a raw terminal display
+    /// column) within `row`, accounting for wide glyphs occupying two
+    /// columns and zero-width marks occupying none.
+    pub fn cell_index_for_column(row: &[Cell], column: usize) -> usize {
+        let mut display_column = 0;
+        for (index, cell) in row.iter().enumerate() {
+            let width = cell.ch.width().unwrap_or(0);
+            if column < display_column + width {
+                return index;
+            }
+            display_column += width;
+        }
+        row.len()
+    }
+
+    /// Render a row of cells back to a plain string with the SGR sequences
+    /// needed to reproduce each cell's style, emitting a fresh run at the
+    /// start so a style active at a wrap boundary is never lost.
+    pub fn render_row(row: &[Cell]) -> String {
+        let mut out = String::new();
+        let mut current: Option<Style> = None;
+        for cell in row {
+            if current != Some(cell.style) {
+                out.push_str(&cell.style.to_sgr());
+                current = Some(cell.style);
+            }
+            out.push(cell.ch);
+        }
+        if current.is_some_and(|style| style != Style::default()) {
+            out.push_str("\x1b[0m");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_ansi_applies_sgr_and_strips_it_from_text() {
+        let line = LogicalLine::from_ansi("\x1b[31mred\x1b[0m plain");
+        let text: String = line.cells.iter().map(|c| c.ch).collect();
+        assert_eq!(text, "red plain");
+        assert_eq!(line.cells[0].style.fg, Some(Color::Named(AnsiColor::Red)));
+        assert_eq!(line.cells[4].style.fg, None);
+    }
+
+    #[test]
+    fn from_ansi_parses_extended_colors() {
+        let indexed = LogicalLine::from_ansi("\x1b[38;5;200mx");
+        assert_eq!(indexed.cells[0].style.fg, Some(Color::Indexed(200)));
+
+        let rgb = LogicalLine::from_ansi("\x1b[48;2;1;2;3mx");
+        assert_eq!(rgb.cells[0].style.bg, Some(Color::Rgb(1, 2, 3)));
+    }
+
+    #[test]
+    fn from_ansi_strips_a_non_csi_escape_instead_of_storing_it_as_text() {
+        let line = LogicalLine::from_ansi("\x1babc");
+        let text: String = line.cells.iter().map(|c| c.ch).collect();
+        assert!(!text.contains('\x1b'));
+        assert_eq!(text, "bc");
+    }
+
+    #[test]
+    fn from_ansi_consumes_non_sgr_csi_without_eating_text() {
+        // `\x1b[2J` (clear screen) has no `m` final byte, so it must be
+        // swallowed without being mistaken for the start of plain text.
+        let line = LogicalLine::from_ansi("\x1b[2Jhello");
+        let text: String = line.cells.iter().map(|c| c.ch).collect();
+        assert_eq!(text, "hello");
+    }
+
+    #[test]
+    fn style_round_trips_through_to_sgr() {
+        let mut style = Style::default();
+        style.apply_sgr(&[1, 31, 44]);
+        let rendered = style.to_sgr();
+        let mut reparsed = Style::default();
+        let params: Vec<u16> = rendered
+            .trim_start_matches("\x1b[")
+            .trim_end_matches('m')
+            .split(';')
+            .filter_map(|p| p.parse().ok())
+            .collect();
+        reparsed.apply_sgr(&params);
+        assert_eq!(style, reparsed);
+    }
+
+    #[test]
+    fn wrap_breaks_on_last_space_before_boundary() {
+        let line = LogicalLine::from_ansi("hello world");
+        let rows = line.wrap(8);
+        let rendered: Vec<String> = rows
+            .iter()
+            .map(|row| row.iter().map(|c| c.ch).collect())
+            .collect();
+        assert_eq!(rendered, vec!["hello ".to_string(), "world".to_string()]);
+    }
+
+    #[test]
+    fn wrap_accounts_for_wide_glyph_width() {
+        // Each '\u{4e2d}' (中) is 2 columns wide, so 4 of them fill an
+        // 8-column row even though there are only 4 cells.
+        let line = LogicalLine::from_ansi("中中中中中中");
+        let rows = line.wrap(8);
+        assert_eq!(rows[0].len(), 4);
+        assert_eq!(rows[1].len(), 2);
+    }
+
+    #[test]
+    fn wrap_never_lets_a_wide_glyph_spill_past_the_boundary() {
+        // 'a' (1 col) + four 中 (2 cols each) = 9 columns; the glyph that
+        // would land on the odd boundary must start the next row instead.
+        let line = LogicalLine::from_ansi("a中中中中");
+        let rows = line.wrap(8);
+        for row in &rows {
+            let row_columns: usize = row.iter().filter_map(|c| c.ch.width()).sum();
+            assert!(row_columns <= 8, "row width {row_columns} exceeds 8");
+        }
+    }
+
+    #[test]
+    fn wrap_reapplies_style_after_a_break() {
+        let line = LogicalLine::from_ansi("\x1b[31mhello world\x1b[0m");
+        let rows = line.wrap(8);
+        assert_eq!(rows[1][0].style.fg, Some(Color::Named(AnsiColor::Red)));
+    }
+}
@@ -0,0 +1,172 @@
+//! Incremental search over `Screen`'s scrollback.
+//!
+//! Matches are found against the de-styled text of each logical line (the
+//! `Cell`s already have their ANSI escapes stripped out during parsing), so
+//! escape bytes embedded in history can never produce a spurious match.
+
+use super::cell::LogicalLine;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Match {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Search {
+    matches: Vec<Match>,
+    current: Option<usize>,
+}
+
+impl Search {
+    /// Build a search over every occurrence of `pattern` in `history`.
+    /// Returns `None` if there are no matches at all.
+    pub fn new(history: &VecDeque<LogicalLine>, pattern: &str, case_insensitive: bool) -> Option<Self> {
+        let matches = find_matches(history, pattern, case_insensitive);
+        if matches.is_empty() {
+            None
+        } else {
+            Some(Self {
+                matches,
+                current: None,
+            })
+        }
+    }
+
+    pub fn current_match(&self) -> Option<&Match> {
+        self.current.and_then(|i| self.matches.get(i))
+    }
+
+    /// Move to the next/previous match, wrapping around at the ends.
+    pub fn advance(&mut self, direction: Direction) -> Option<&Match> {
+        let len = self.matches.len();
+        if len == 0 {
+            return None;
+        }
+        self.current = Some(match (self.current, direction) {
+            (None, Direction::Forward) => 0,
+            (None, Direction::Backward) => len - 1,
+            (Some(i), Direction::Forward) => (i + 1) % len,
+            (Some(i), Direction::Backward) => (i + len - 1) % len,
+        });
+        self.current_match()
+    }
+
+    /// Select the first match at/after `line` (forward) or at/before it
+    /// (backward), wrapping around if nothing qualifies.
+    pub fn seek_from(&mut self, line: usize, direction: Direction) -> Option<&Match> {
+        let len = self.matches.len();
+        if len == 0 {
+            return None;
+        }
+        let index = match direction {
+            Direction::Forward => self.matches.iter().position(|m| m.line >= line).unwrap_or(0),
+            Direction::Backward => self
+                .matches
+                .iter()
+                .rposition(|m| m.line <= line)
+                .unwrap_or(len - 1),
+        };
+        self.current = Some(index);
+        self.current_match()
+    }
+
+    /// `Some(true)` if `(line, column)` is the current match, `Some(false)`
+    /// if it's a different match, `None` if it isn't a match at all.
+    pub fn contains(&self, line: usize, column: usize) -> Option<bool> {
+        self.matches
+            .iter()
+            .enumerate()
+            .find(|(_, m)| m.line == line && column >= m.start && column < m.end)
+            .map(|(i, _)| Some(i) == self.current)
+    }
+}
+
+fn find_matches(history: &VecDeque<LogicalLine>, pattern: &str, case_insensitive: bool) -> Vec<Match> {
+    // `to_lowercase()` can yield more than one char for a handful of locale
+    // foldings; take just the first so every folded char still lines up
+    // 1:1 with a cell, which `Match::start`/`end` index into.
+    let fold = |c: char| {
+        if case_insensitive {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    for (line, logical) in history.iter().enumerate() {
+        let haystack: Vec<char> = logical.cells.iter().map(|cell| fold(cell.ch)).collect();
+        if haystack.len() < pattern.len() {
+            continue;
+        }
+        for start in 0..=haystack.len() - pattern.len() {
+            if haystack[start..start + pattern.len()] == pattern[..] {
+                matches.push(Match {
+                    line,
+                    start,
+                    end: start + pattern.len(),
+                });
+            }
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(lines: &[&str]) -> VecDeque<LogicalLine> {
+        lines.iter().map(|line| LogicalLine::from_ansi(line)).collect()
+    }
+
+    #[test]
+    fn find_matches_is_case_insensitive_when_requested() {
+        let history = history(&["Hello World"]);
+        let matches = find_matches(&history, "WORLD", true);
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].start, matches[0].end), (6, 11));
+
+        assert!(find_matches(&history, "WORLD", false).is_empty());
+    }
+
+    #[test]
+    fn find_matches_folds_non_ascii_case() {
+        let history = history(&["Café"]);
+        let matches = find_matches(&history, "CAFÉ", true);
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[test]
+    fn advance_wraps_around_in_both_directions() {
+        let history = history(&["foo", "foo"]);
+        let mut search = Search::new(&history, "foo", false).unwrap();
+        assert_eq!(search.advance(Direction::Forward).unwrap().line, 0);
+        assert_eq!(search.advance(Direction::Forward).unwrap().line, 1);
+        assert_eq!(search.advance(Direction::Forward).unwrap().line, 0);
+        assert_eq!(search.advance(Direction::Backward).unwrap().line, 1);
+    }
+
+    #[test]
+    fn seek_from_wraps_when_nothing_qualifies() {
+        let history = history(&["foo", "bar", "foo"]);
+        let mut search = Search::new(&history, "foo", false).unwrap();
+        // No match at or after line 3, so forward seek wraps to the first.
+        assert_eq!(search.seek_from(3, Direction::Forward).unwrap().line, 0);
+        // Line 1 itself has no match, but a match at or before it exists.
+        assert_eq!(search.seek_from(1, Direction::Backward).unwrap().line, 0);
+    }
+}
@@ -0,0 +1,133 @@
+//! Text selection over `Screen`'s styled history.
+//!
+//! A selection is anchored to a logical line index and a cell column within
+//! it, not to a wrapped display row, so it stays correct across a resize
+//! that reflows the affected lines differently.
+
+use super::cell::{Cell, LogicalLine};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Point {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Word,
+    Line,
+}
+
+#[derive(Debug, Clone)]
+pub struct Selection {
+    anchor: Point,
+    cursor: Point,
+    mode: Mode,
+}
+
+impl Selection {
+    pub fn new(anchor: Point, mode: Mode) -> Self {
+        Self {
+            anchor,
+            cursor: anchor,
+            mode,
+        }
+    }
+
+    pub fn update_cursor(&mut self, cursor: Point) {
+        self.cursor = cursor;
+    }
+
+    /// Anchor/cursor in reading order, then expanded to the selection mode's
+    /// granularity (a whole word, or a whole line).
+    pub fn normalized(&self, history: &VecDeque<LogicalLine>) -> (Point, Point) {
+        let (mut start, mut end) = if self.anchor <= self.cursor {
+            (self.anchor, self.cursor)
+        } else {
+            (self.cursor, self.anchor)
+        };
+
+        match self.mode {
+            Mode::Normal => {}
+            Mode::Line => {
+                start.column = 0;
+                end.column = history.get(end.line).map_or(0, |line| line.cells.len());
+            }
+            Mode::Word => {
+                if let Some(line) = history.get(start.line) {
+                    start.column = word_start(&line.cells, start.column);
+                }
+                if let Some(line) = history.get(end.line) {
+                    end.column = word_end(&line.cells, end.column);
+                }
+            }
+        }
+        (start, end)
+    }
+
+    /// Whether `column` on logical line `line` falls within this selection.
+    pub fn contains(&self, history: &VecDeque<LogicalLine>, line: usize, column: usize) -> bool {
+        let (start, end) = self.normalized(history);
+        if line < start.line || line > end.line {
+            return false;
+        }
+        let from = if line == start.line { start.column } else { 0 };
+        let to = if line == end.line {
+            end.column
+        } else {
+            usize::MAX
+        };
+        column >= from && column < to
+    }
+}
+
+fn word_start(cells: &[Cell], column: usize) -> usize {
+    let mut start = column.min(cells.len());
+    while start > 0 && !cells[start - 1].ch.is_whitespace() {
+        start -= 1;
+    }
+    start
+}
+
+fn word_end(cells: &[Cell], column: usize) -> usize {
+    let mut end = column.min(cells.len());
+    while end < cells.len() && !cells[end].ch.is_whitespace() {
+        end += 1;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(s: &str) -> Vec<Cell> {
+        s.chars()
+            .map(|ch| Cell {
+                ch,
+                style: Default::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn word_start_stops_at_preceding_whitespace() {
+        let line = cells("hello world");
+        assert_eq!(word_start(&line, 8), 6);
+    }
+
+    #[test]
+    fn word_end_stops_at_following_whitespace() {
+        let line = cells("hello world");
+        assert_eq!(word_end(&line, 8), 11);
+    }
+
+    #[test]
+    fn word_start_and_end_clamp_to_line_bounds() {
+        let line = cells("hello");
+        assert_eq!(word_start(&line, 0), 0);
+        assert_eq!(word_end(&line, 100), 5);
+    }
+}
@@ -0,0 +1,57 @@
+//! Column-measuring helpers.
+//!
+//! Terminal columns don't line up with bytes or even `char`s: wide glyphs
+//! (CJK, emoji) occupy two columns, zero-width combining marks occupy none,
+//! and a grapheme cluster must never be split across a byte boundary. Screen
+//! uses these helpers everywhere it used to compare raw lengths against
+//! `self.width`.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The number of terminal columns `s` occupies.
+pub fn columns(s: &str) -> usize {
+    s.width()
+}
+
+/// The longest suffix of `s`, split only on grapheme-cluster boundaries,
+/// that occupies at most `width` columns.
+pub fn tail_columns(s: &str, width: usize) -> &str {
+    let graphemes: Vec<(usize, &str)> = s.grapheme_indices(true).collect();
+    let mut used = 0;
+    for &(byte_index, grapheme) in graphemes.iter().rev() {
+        let w = grapheme.width();
+        if used + w > width {
+            return &s[byte_index + grapheme.len()..];
+        }
+        used += w;
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_counts_wide_glyphs_as_two() {
+        assert_eq!(columns("ab"), 2);
+        assert_eq!(columns("中中"), 4);
+    }
+
+    #[test]
+    fn tail_columns_returns_whole_string_when_it_fits() {
+        assert_eq!(tail_columns("hello", 10), "hello");
+    }
+
+    #[test]
+    fn tail_columns_truncates_to_a_grapheme_boundary() {
+        assert_eq!(tail_columns("hello world", 5), "world");
+    }
+
+    #[test]
+    fn tail_columns_never_splits_a_wide_glyph() {
+        // Each glyph is 2 columns; a width of 3 can only fit one of them.
+        assert_eq!(tail_columns("中中", 3), "中");
+    }
+}